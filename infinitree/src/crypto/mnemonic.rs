@@ -0,0 +1,109 @@
+//! A BIP39 recovery-phrase [`KeySource`], as an alternative to
+//! [`YubikeyCR::with_credentials`](super::YubikeyCR::with_credentials)
+//! and the plain username/password symmetric mode.
+//!
+//! Where those modes stretch a chosen username/password into the
+//! master key via `derive_argon2`, this mode lets the master key be
+//! written down on paper as a human-transcribable 12-24 word phrase
+//! and recovered from it later. The resulting [`KeySource`] is a plain
+//! `Symmetric` one; [`YubikeyCR`](super::YubikeyCR) has no constructor
+//! that takes it, so it is not layered under a Yubikey second factor.
+use super::{symmetric::Symmetric, *};
+
+use bip39::{Language, Mnemonic};
+use secrecy::{ExposeSecret, SecretString};
+use std::sync::Arc;
+
+/// 128 bits of entropy yields a 12-word phrase, 256 bits a 24-word one.
+pub const DEFAULT_ENTROPY_BITS: usize = 256;
+
+/// The only entropy sizes BIP39 defines a wordlist mapping for.
+const SUPPORTED_ENTROPY_BITS: [usize; 5] = [128, 160, 192, 224, 256];
+
+/// Generate a fresh recovery phrase and the [`KeySource`] it seeds, for
+/// a brand new tree. `entropy_bits` must be one of the BIP39-supported
+/// sizes between 128 and 256 (in steps of 32); see
+/// [`DEFAULT_ENTROPY_BITS`] for a sane default. `passphrase` is the
+/// optional BIP39 extension word; pass an empty string to generate a
+/// phrase that recovers with [`from_mnemonic`] and an empty passphrase.
+///
+/// The returned phrase is the only copy of the tree's root secret --
+/// the caller is responsible for displaying/storing it.
+pub fn generate_mnemonic(
+    entropy_bits: usize,
+    passphrase: SecretString,
+) -> Result<(SecretString, KeySource)> {
+    if !SUPPORTED_ENTROPY_BITS.contains(&entropy_bits) {
+        return Err(CryptoError::Fatal);
+    }
+
+    let word_count = entropy_bits / 32 * 3;
+    let mnemonic =
+        Mnemonic::generate_in(Language::English, word_count).map_err(|_| CryptoError::Fatal)?;
+
+    let phrase: SecretString = mnemonic.to_string().into();
+    let key = keysource_from_mnemonic(&mnemonic, &passphrase)?;
+
+    Ok((phrase, key))
+}
+
+/// Recover the [`KeySource`] for an existing tree from its recovery
+/// phrase. `passphrase` is the optional BIP39 extension word; pass an
+/// empty string if the phrase wasn't generated with one.
+pub fn from_mnemonic(phrase: SecretString, passphrase: SecretString) -> Result<KeySource> {
+    let mnemonic = Mnemonic::parse_in_normalized(Language::English, phrase.expose_secret())
+        .map_err(|_| CryptoError::Fatal)?;
+
+    keysource_from_mnemonic(&mnemonic, &passphrase)
+}
+
+fn keysource_from_mnemonic(mnemonic: &Mnemonic, passphrase: &SecretString) -> Result<KeySource> {
+    // BIP39's own seed stretch: PBKDF2-HMAC-SHA512, 2048 rounds, salt
+    // `"mnemonic" || passphrase`. We only take the first KEY_SIZE bytes
+    // of the 64-byte seed; the rest is discarded rather than truncating
+    // the PBKDF2 output, since that's what `to_seed` already hands us.
+    let seed = mnemonic.to_seed(passphrase.expose_secret());
+
+    let master_key = {
+        let mut buf = [0; KEY_SIZE];
+        buf.copy_from_slice(&seed[..KEY_SIZE]);
+        RawKey::new(buf)
+    };
+
+    let random = SystemRandom::new();
+    Ok(Arc::new(Symmetric {
+        master_key,
+        convergence_key: generate_key(&random)?,
+    }))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn generate_then_recover_roundtrip() {
+        let passphrase: SecretString = "a passphrase".to_string().into();
+        let (phrase, generated) =
+            generate_mnemonic(DEFAULT_ENTROPY_BITS, passphrase.clone()).unwrap();
+
+        let recovered = from_mnemonic(phrase, passphrase).unwrap();
+
+        // expose_convergence_key() is random per call, so it can't tell
+        // master keys apart; round-trip through seal/open_root instead,
+        // which only succeeds if the two KeySources share a master key.
+        let header = CleartextHeader {
+            root_ptr: Default::default(),
+            key: generated.clone(),
+        };
+        let sealed = generated.seal_root(header).unwrap();
+        let opened = recovered.open_root(sealed).unwrap();
+        assert_eq!(opened.root_ptr, RawChunkPointer::default());
+    }
+
+    #[test]
+    fn rejects_unsupported_entropy_bits() {
+        assert!(generate_mnemonic(200, "".to_string().into()).is_err());
+        assert!(generate_mnemonic(64, "".to_string().into()).is_err());
+    }
+}
@@ -24,6 +24,121 @@ const HEADER_PAYLOAD: usize =
 const HEADER_CYPHERTEXT: usize =
     size_of::<SealedHeader>() - size_of::<Nonce>() - size_of::<Challenge>();
 
+/// The last three bytes of the otherwise-random [`Challenge`] buffer are
+/// repurposed to carry a magic byte and the [`CipherSuite`] discriminants.
+/// The Yubikey HMAC challenge-response has no requirement on the entropy
+/// of its input, so losing 3 bytes out of 64 is immaterial, and it lets
+/// the suite be read back *before* the header ciphertext is touched,
+/// which is exactly when we need to know which AEAD to open it with.
+///
+/// The magic byte exists because headers sealed before `CipherSuite` was
+/// introduced filled all 64 challenge bytes with random data -- reading
+/// the last two as discriminants unconditionally would misinterpret that
+/// randomness as a suite almost every time, and fail to open a repository
+/// that predates this feature. A header is only treated as carrying suite
+/// discriminants if its magic byte matches; otherwise it's assumed to be
+/// one of those legacy headers and opened with [`CipherSuite::default`].
+const CHALLENGE_MAGIC_BYTE: usize = size_of::<Challenge>() - 3;
+const CHALLENGE_AEAD_BYTE: usize = size_of::<Challenge>() - 2;
+const CHALLENGE_KDF_BYTE: usize = size_of::<Challenge>() - 1;
+const CHALLENGE_SUITE_MAGIC: u8 = 0xc5;
+
+/// AEAD primitive used to seal a repository's root header.
+///
+/// Stored as a single discriminant byte alongside the header so that a
+/// repository keeps opening with whichever suite it was created under,
+/// even after this library's default changes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AeadAlgorithm {
+    AesGcm = 0,
+    ChaCha20Poly1305 = 1,
+}
+
+impl TryFrom<u8> for AeadAlgorithm {
+    type Error = CryptoError;
+
+    fn try_from(value: u8) -> Result<Self> {
+        match value {
+            0 => Ok(AeadAlgorithm::AesGcm),
+            1 => Ok(AeadAlgorithm::ChaCha20Poly1305),
+            _ => Err(CryptoError::Fatal),
+        }
+    }
+}
+
+/// Key-derivation function used to stretch a password into a master key.
+///
+/// Only `Argon2` is implemented today, but the discriminant is persisted
+/// from the start so a future KDF can be added without breaking
+/// repositories sealed under this one.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum KdfAlgorithm {
+    Argon2 = 0,
+}
+
+impl TryFrom<u8> for KdfAlgorithm {
+    type Error = CryptoError;
+
+    fn try_from(value: u8) -> Result<Self> {
+        match value {
+            0 => Ok(KdfAlgorithm::Argon2),
+            _ => Err(CryptoError::Fatal),
+        }
+    }
+}
+
+/// The cipher suite a [`YubikeyCR`] repository was sealed under.
+///
+/// This currently only governs the 512-byte root header: the
+/// chunk/index/storage data crypto reached via [`CryptoScheme::chunk_key`]
+/// and friends still goes through whatever single primitive
+/// `ChunkKey`/`CryptoProvider` hardwire in `crypto::mod`, and the plain
+/// `Symmetric` `KeySource` (used without a Yubikey at all) never
+/// constructs or consults a `CipherSuite` in the first place. Making the
+/// repository's data, not just its header, portable across suites needs
+/// those two to take a `CipherSuite` as well; that's out of reach from
+/// this module alone.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CipherSuite {
+    pub aead: AeadAlgorithm,
+    pub kdf: KdfAlgorithm,
+}
+
+impl Default for CipherSuite {
+    fn default() -> Self {
+        CipherSuite {
+            aead: AeadAlgorithm::AesGcm,
+            kdf: KdfAlgorithm::Argon2,
+        }
+    }
+}
+
+/// Build an AEAD sealing key for the given suite, analogous to the
+/// single-algorithm `get_aead` but able to reconstruct whichever
+/// primitive a stored discriminant calls for.
+fn get_aead_for(alg: AeadAlgorithm, key: RawKey) -> Result<aead::LessSafeKey> {
+    let algorithm = match alg {
+        AeadAlgorithm::AesGcm => &aead::AES_256_GCM,
+        AeadAlgorithm::ChaCha20Poly1305 => &aead::CHACHA20_POLY1305,
+    };
+
+    let unbound = aead::UnboundKey::new(algorithm, key.expose_secret()).map_err(|_| CryptoError::Fatal)?;
+    Ok(aead::LessSafeKey::new(unbound))
+}
+
+/// Stretch `username`/`password` into a master key with whichever KDF
+/// `suite.kdf` calls for. Only `Argon2` exists today, but dispatching
+/// through this match -- instead of calling `derive_argon2` unconditionally
+/// -- means adding a second `KdfAlgorithm` variant is a compile error here
+/// until it's actually wired up, rather than a silently ignored discriminant.
+fn derive_master_key(kdf: KdfAlgorithm, username: &[u8], password: &[u8]) -> Result<RawKey> {
+    match kdf {
+        KdfAlgorithm::Argon2 => {
+            derive_argon2(b"zerostash.com yubikey cr master key", username, password)
+        }
+    }
+}
+
 /// This mode's behaviour is equivalent to the
 /// [`UsernamePassword`](crate::keys::UsernamePassword) `KeySource`, but
 /// adds a second factor.
@@ -45,26 +160,54 @@ const HEADER_CYPHERTEXT: usize =
 /// ```text
 /// encrypt(root[88] || mode[1] || convergence_key[32] || 0[..]) || mac[16] || nonce[12] || yubikey_challenge[64]
 /// ```
+///
+/// The last three bytes of `yubikey_challenge` are not random: a magic
+/// byte followed by the [`CipherSuite`] (an AEAD-algorithm and a
+/// KDF-algorithm discriminant) this header was sealed under, so
+/// [`YubikeyCR::open_root`] can reconstruct the right primitives before
+/// it even attempts to decrypt the rest of the header. The magic byte
+/// lets a header sealed before `CipherSuite` existed -- whose challenge
+/// is fully random -- still be recognized as legacy and opened with the
+/// suite this library used exclusively back then, instead of having
+/// those random bytes misread as a bogus suite. This keeps a repository
+/// created under one suite, or before suites existed at all, openable
+/// after a library upgrade changes the default.
 pub struct YubikeyCR {
     inner: KeySource,
     master_key: RawKey,
     mode: Mode,
+    suite: CipherSuite,
     ykconfig: yubico_manager::config::Config,
 }
 
+/// Abstracts the Yubikey HMAC challenge-response, so the header
+/// seal/open crypto below can be driven by a real device or, in tests,
+/// by a stub that never touches hardware.
+trait ChallengeResponder {
+    fn respond(&self, challenge: &Challenge) -> Result<Response>;
+}
+
+struct YubikeyResponder(yubico_manager::config::Config);
+
+impl ChallengeResponder for YubikeyResponder {
+    fn respond(&self, challenge: &Challenge) -> Result<Response> {
+        let mut yk = Yubico::new();
+        Ok(yk
+            .challenge_response_hmac(challenge, self.0.clone())
+            .map_err(|_| CryptoError::Fatal)?
+            .0)
+    }
+}
+
 /// blake3_kdf(ctx, master_key || yk_hmac_response(challenge))
 fn header_key(
     master_key: &RawKey,
     challenge: Challenge,
-    config: yubico_manager::config::Config,
+    responder: &impl ChallengeResponder,
 ) -> Result<RawKey> {
     let mut k = [0; KEY_SIZE + size_of::<Response>()];
 
-    let mut yk = Yubico::new();
-    let resp = yk
-        .challenge_response_hmac(&challenge, config)
-        .map_err(|_| CryptoError::Fatal)?
-        .0;
+    let resp = responder.respond(&challenge)?;
 
     k[..KEY_SIZE].copy_from_slice(master_key.expose_secret());
     k[KEY_SIZE..].copy_from_slice(&resp);
@@ -75,8 +218,9 @@ fn header_key(
 fn seal_header(
     master_key: &RawKey,
     mode: Mode,
+    suite: CipherSuite,
     header: CleartextHeader,
-    ykconfig: yubico_manager::config::Config,
+    responder: &impl ChallengeResponder,
 ) -> Result<SealedHeader> {
     let mut output = SealedHeader::default();
     let random = SystemRandom::new();
@@ -88,6 +232,9 @@ fn seal_header(
     let challenge = {
         let mut buf = [0; size_of::<Challenge>()];
         random.fill(&mut buf)?;
+        buf[CHALLENGE_MAGIC_BYTE] = CHALLENGE_SUITE_MAGIC;
+        buf[CHALLENGE_AEAD_BYTE] = suite.aead as u8;
+        buf[CHALLENGE_KDF_BYTE] = suite.kdf as u8;
         buf
     };
 
@@ -99,7 +246,7 @@ fn seal_header(
     output[HEADER_CYPHERTEXT..HEADER_CYPHERTEXT + size_of::<Nonce>()]
         .copy_from_slice(nonce.as_ref());
 
-    let aead = get_aead(header_key(master_key, challenge, ykconfig)?);
+    let aead = get_aead_for(suite.aead, header_key(master_key, challenge, responder)?)?;
     let tag =
         aead.seal_in_place_separate_tag(nonce, aead::Aad::empty(), &mut output[..HEADER_PAYLOAD])?;
 
@@ -111,15 +258,76 @@ fn seal_header(
     Ok(output)
 }
 
+/// Mirrors `seal_header` as it behaved before `CipherSuite` existed: the
+/// whole challenge is random -- no magic byte, no discriminants -- and
+/// the header is always sealed under the default suite. Used only to
+/// build a fixture for testing that `open_header` still recognizes and
+/// opens headers of this shape.
+#[cfg(test)]
+fn seal_header_legacy(
+    master_key: &RawKey,
+    mode: Mode,
+    header: CleartextHeader,
+    responder: &impl ChallengeResponder,
+) -> Result<SealedHeader> {
+    let mut output = SealedHeader::default();
+    let random = SystemRandom::new();
+    let nonce = {
+        let mut buf = Nonce::default();
+        random.fill(&mut buf)?;
+        aead::Nonce::assume_unique_for_key(buf)
+    };
+    let mut challenge = [0; size_of::<Challenge>()];
+    random.fill(&mut challenge)?;
+    // Vanishingly unlikely, but keep the fixture deterministically
+    // legacy-shaped rather than leaving a 1/256 chance of flaking.
+    if challenge[CHALLENGE_MAGIC_BYTE] == CHALLENGE_SUITE_MAGIC {
+        challenge[CHALLENGE_MAGIC_BYTE] ^= 0x01;
+    }
+
+    let pos = mode.encode_root_to(&mut output, &header)?;
+    debug_assert!(pos <= HEADER_CYPHERTEXT);
+
+    output[HEADER_CYPHERTEXT..HEADER_CYPHERTEXT + size_of::<Nonce>()]
+        .copy_from_slice(nonce.as_ref());
+
+    let aead = get_aead_for(
+        AeadAlgorithm::AesGcm,
+        header_key(master_key, challenge, responder)?,
+    )?;
+    let tag =
+        aead.seal_in_place_separate_tag(nonce, aead::Aad::empty(), &mut output[..HEADER_PAYLOAD])?;
+
+    output[HEADER_PAYLOAD..HEADER_PAYLOAD + size_of::<Tag>()].copy_from_slice(tag.as_ref());
+    output[HEADER_CYPHERTEXT + size_of::<Nonce>()..].copy_from_slice(&challenge);
+
+    Ok(output)
+}
+
 fn open_header(
     master_key: RawKey,
     mut sealed: SealedHeader,
     ykconfig: yubico_manager::config::Config,
+    responder: &impl ChallengeResponder,
 ) -> Result<CleartextHeader> {
     let mut challenge = [0; size_of::<Challenge>()];
     challenge.copy_from_slice(&sealed[HEADER_CYPHERTEXT + size_of::<Nonce>()..]);
 
-    let aead = get_aead(header_key(&master_key, challenge, ykconfig.clone())?);
+    // Headers sealed before `CipherSuite` existed filled every challenge
+    // byte with randomness, so the magic byte only matches by chance
+    // (~1/256) on one of those legacy headers. Anything else is assumed
+    // legacy and opened with the suite that was this library's only
+    // option back then.
+    let suite = if challenge[CHALLENGE_MAGIC_BYTE] == CHALLENGE_SUITE_MAGIC {
+        CipherSuite {
+            aead: AeadAlgorithm::try_from(challenge[CHALLENGE_AEAD_BYTE])?,
+            kdf: KdfAlgorithm::try_from(challenge[CHALLENGE_KDF_BYTE])?,
+        }
+    } else {
+        CipherSuite::default()
+    };
+
+    let aead = get_aead_for(suite.aead, header_key(&master_key, challenge, responder)?)?;
     let nonce = {
         let mut buf = Nonce::default();
         buf.copy_from_slice(&sealed[HEADER_CYPHERTEXT..HEADER_CYPHERTEXT + size_of::<Nonce>()]);
@@ -146,6 +354,7 @@ fn open_header(
     let key: KeySource = Arc::new(YubikeyCR {
         inner,
         mode,
+        suite,
         ykconfig,
         master_key,
     });
@@ -158,10 +367,22 @@ impl YubikeyCR {
         username: SecretString,
         password: SecretString,
         ykconfig: yubico_manager::config::Config,
+    ) -> Result<KeySource> {
+        Self::with_credentials_and_suite(username, password, ykconfig, CipherSuite::default())
+    }
+
+    /// Like [`with_credentials`](Self::with_credentials), but pins the
+    /// AEAD/KDF suite the repository is sealed under, rather than
+    /// taking this library's current default.
+    pub fn with_credentials_and_suite(
+        username: SecretString,
+        password: SecretString,
+        ykconfig: yubico_manager::config::Config,
+        suite: CipherSuite,
     ) -> Result<KeySource> {
         let random = SystemRandom::new();
-        let master_key = derive_argon2(
-            b"zerostash.com yubikey cr master key",
+        let master_key = derive_master_key(
+            suite.kdf,
             username.expose_secret().as_bytes(),
             password.expose_secret().as_bytes(),
         )?;
@@ -173,6 +394,7 @@ impl YubikeyCR {
             }),
             master_key,
             mode: Mode::Symmetric,
+            suite,
             ykconfig,
         }))
     }
@@ -184,11 +406,22 @@ impl CryptoScheme for YubikeyCR {
     }
 
     fn open_root(self: Arc<Self>, header: SealedHeader) -> Result<CleartextHeader> {
-        open_header(self.master_key.clone(), header, self.ykconfig.clone())
+        open_header(
+            self.master_key.clone(),
+            header,
+            self.ykconfig.clone(),
+            &YubikeyResponder(self.ykconfig.clone()),
+        )
     }
 
     fn seal_root(&self, header: CleartextHeader) -> Result<SealedHeader> {
-        seal_header(&self.master_key, self.mode, header, self.ykconfig.clone())
+        seal_header(
+            &self.master_key,
+            self.mode,
+            self.suite,
+            header,
+            &YubikeyResponder(self.ykconfig.clone()),
+        )
     }
 
     fn chunk_key(&self) -> Result<ChunkKey> {
@@ -210,6 +443,17 @@ impl CryptoScheme for YubikeyCR {
 
 #[cfg(test)]
 mod test {
+    /// Always answers with the same canned response, so the header
+    /// seal/open crypto -- including non-default `CipherSuite`s -- can
+    /// be exercised without a physical Yubikey.
+    struct StubResponder(super::Response);
+
+    impl super::ChallengeResponder for StubResponder {
+        fn respond(&self, _challenge: &super::Challenge) -> super::Result<super::Response> {
+            Ok(self.0)
+        }
+    }
+
     #[test]
     fn userpass_encrypt_decrypt() {
         use super::{CleartextHeader, ExposeSecret, RawChunkPointer, YubikeyCR};
@@ -256,4 +500,60 @@ mod test {
             convergence_key.expose_secret()
         );
     }
+
+    #[test]
+    fn suite_roundtrip_with_chacha20poly1305_without_hardware() {
+        use super::{
+            generate_key, open_header, seal_header, AeadAlgorithm, CipherSuite, CleartextHeader,
+            KdfAlgorithm, Mode, RawChunkPointer, RawKey, SystemRandom, KEY_SIZE,
+        };
+
+        let master_key = RawKey::new([11; KEY_SIZE]);
+        let responder = StubResponder([22; 20]);
+        let suite = CipherSuite {
+            aead: AeadAlgorithm::ChaCha20Poly1305,
+            kdf: KdfAlgorithm::Argon2,
+        };
+
+        let random = SystemRandom::new();
+        let key = Mode::Symmetric.keysource(master_key.clone(), generate_key(&random).unwrap());
+        let header = CleartextHeader {
+            root_ptr: Default::default(),
+            key,
+        };
+
+        let sealed = seal_header(&master_key, Mode::Symmetric, suite, header, &responder).unwrap();
+        let opened = open_header(
+            master_key,
+            sealed,
+            Default::default(),
+            &responder,
+        )
+        .unwrap();
+
+        assert_eq!(opened.root_ptr, RawChunkPointer::default());
+    }
+
+    #[test]
+    fn legacy_header_without_suite_magic_opens_under_default_suite() {
+        use super::{
+            generate_key, open_header, seal_header_legacy, CleartextHeader, Mode,
+            RawChunkPointer, RawKey, SystemRandom, KEY_SIZE,
+        };
+
+        let master_key = RawKey::new([33; KEY_SIZE]);
+        let responder = StubResponder([44; 20]);
+
+        let random = SystemRandom::new();
+        let key = Mode::Symmetric.keysource(master_key.clone(), generate_key(&random).unwrap());
+        let header = CleartextHeader {
+            root_ptr: Default::default(),
+            key,
+        };
+
+        let sealed = seal_header_legacy(&master_key, Mode::Symmetric, header, &responder).unwrap();
+        let opened = open_header(master_key, sealed, Default::default(), &responder).unwrap();
+
+        assert_eq!(opened.root_ptr, RawChunkPointer::default());
+    }
 }
@@ -19,9 +19,22 @@ pub use reader::{AEADReader, Reader};
 mod writer;
 pub use writer::{AEADWriter, Writer};
 
+mod write_balancer;
+pub use write_balancer::RoundRobinBalancer;
+
 mod bufferedstream;
 pub use bufferedstream::*;
 
+mod segment;
+pub use segment::{
+    check_segment_size, SegmentDecryptor, SegmentEncryptor, MAX_SEGMENT_SIZE, MIN_SEGMENT_SIZE,
+};
+
+#[cfg(feature = "tokio")]
+mod asyncio;
+#[cfg(feature = "tokio")]
+pub use asyncio::EncryptedStream;
+
 pub mod serializer;
 
 mod id;
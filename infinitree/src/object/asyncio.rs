@@ -0,0 +1,353 @@
+//! `tokio` [`AsyncRead`]/[`AsyncWrite`] streams over encrypted objects.
+//!
+//! Built on the same segmented-AEAD framing [`SegmentEncryptor`]/
+//! [`SegmentDecryptor`] introduced for large objects, so a caller can
+//! pipe an arbitrarily large transfer through [`EncryptedStream`]
+//! without ever materializing a whole object plaintext in memory.
+//!
+//! Each buffered [`BLOCK_SIZE`] plaintext block becomes one segment of a
+//! single logical object for the lifetime of the stream; on the wire
+//! each sealed segment is preceded by an explicit little-endian length
+//! prefix, so the reading side never has to infer object boundaries
+//! from stream position. [`EncryptedStream::split`] hands back
+//! independent owned halves backed by `tokio`'s own `split`/`unsplit`.
+//!
+//! The write and read sides are keyed from separate `(object_id,
+//! base_iv)` pairs, passed in as `write` and `read` respectively: one
+//! peer's `write` pair must be the other peer's `read` pair, and vice
+//! versa. This is required, not just for hygiene -- if both peers used
+//! the *same* pair for both directions (as the most obvious API would
+//! encourage), the two directions would derive the identical segment
+//! key and walk the identical nonce sequence, so each peer's outgoing
+//! segment `N` would reuse the exact `(key, nonce)` its own incoming
+//! segment `N` uses, which is catastrophic for any AEAD. Swapping the
+//! pair between peers keeps the two directions' key material disjoint
+//! and makes a full-duplex transfer safe to drive concurrently.
+use super::{check_segment_size, ObjectId, Result, SegmentDecryptor, SegmentEncryptor};
+use crate::{crypto::ChunkKey, BLOCK_SIZE};
+
+use std::{
+    io,
+    pin::Pin,
+    task::{Context, Poll},
+};
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+
+const LEN_PREFIX: usize = 4;
+const TAG_LEN: usize = ring::aead::MAX_TAG_LEN;
+
+enum ReadState {
+    Len { buf: [u8; LEN_PREFIX], filled: usize },
+    Body { buf: Vec<u8>, filled: usize },
+    Plain { buf: Vec<u8>, pos: usize },
+    Done,
+}
+
+/// An encrypted, length-framed `AsyncRead + AsyncWrite` stream.
+///
+/// See the [module docs](self) for the framing this builds on.
+pub struct EncryptedStream<S> {
+    inner: S,
+    encryptor: SegmentEncryptor,
+    plaintext: Vec<u8>,
+    out: Vec<u8>,
+    out_pos: usize,
+    final_segment_sent: bool,
+    decryptor: SegmentDecryptor,
+    final_segment_received: bool,
+    read_state: ReadState,
+}
+
+impl<S> EncryptedStream<S> {
+    /// Wrap `inner` with an encrypted stream that seals outgoing
+    /// segments under `write` and opens incoming segments under `read`.
+    ///
+    /// `write` and `read` are each an `(object_id, base_iv)` pair. For
+    /// two peers to talk to each other, each one's `write` pair must be
+    /// the *other* peer's `read` pair -- see the [module docs](self)
+    /// for why passing the same pair for both directions is unsafe.
+    pub fn new(
+        inner: S,
+        crypto: &ChunkKey,
+        write: (ObjectId, [u8; 12]),
+        read: (ObjectId, [u8; 12]),
+    ) -> Result<Self> {
+        check_segment_size(BLOCK_SIZE)?;
+
+        let (write_id, write_iv) = write;
+        let (read_id, read_iv) = read;
+
+        Ok(EncryptedStream {
+            inner,
+            encryptor: SegmentEncryptor::new(crypto, &write_id, write_iv)?,
+            plaintext: Vec::with_capacity(BLOCK_SIZE),
+            out: Vec::new(),
+            out_pos: 0,
+            final_segment_sent: false,
+            decryptor: SegmentDecryptor::new(crypto, &read_id, read_iv)?,
+            final_segment_received: false,
+            read_state: ReadState::Len {
+                buf: [0; LEN_PREFIX],
+                filled: 0,
+            },
+        })
+    }
+}
+
+impl<S: AsyncWrite + Unpin> EncryptedStream<S> {
+    fn seal_plaintext(&mut self, is_last: bool) -> Result<()> {
+        let mut segment = std::mem::take(&mut self.plaintext);
+        let tag = self.encryptor.seal_segment(&mut segment, is_last)?;
+
+        self.out
+            .extend_from_slice(&(segment.len() as u32 + TAG_LEN as u32).to_le_bytes());
+        self.out.extend_from_slice(&segment);
+        self.out.extend_from_slice(tag.as_ref());
+
+        Ok(())
+    }
+
+    fn poll_drain_out(&mut self, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        while self.out_pos < self.out.len() {
+            let n = match Pin::new(&mut self.inner).poll_write(cx, &self.out[self.out_pos..]) {
+                Poll::Ready(Ok(n)) => n,
+                Poll::Ready(Err(e)) => return Poll::Ready(Err(e)),
+                Poll::Pending => return Poll::Pending,
+            };
+            self.out_pos += n;
+        }
+
+        self.out.clear();
+        self.out_pos = 0;
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl<S: AsyncWrite + Unpin> AsyncWrite for EncryptedStream<S> {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        match self.poll_drain_out(cx) {
+            Poll::Ready(Ok(())) => {}
+            Poll::Ready(Err(e)) => return Poll::Ready(Err(e)),
+            Poll::Pending => return Poll::Pending,
+        }
+
+        let room = BLOCK_SIZE - self.plaintext.len();
+        let take = room.min(buf.len());
+        self.plaintext.extend_from_slice(&buf[..take]);
+
+        if self.plaintext.len() == BLOCK_SIZE {
+            self.seal_plaintext(false)
+                .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        }
+
+        Poll::Ready(Ok(take))
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        match self.as_mut().poll_drain_out(cx) {
+            Poll::Ready(Ok(())) => Pin::new(&mut self.inner).poll_flush(cx),
+            other => other,
+        }
+    }
+
+    fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        if !self.final_segment_sent {
+            self.seal_plaintext(true)
+                .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+            self.final_segment_sent = true;
+        }
+
+        match self.as_mut().poll_drain_out(cx) {
+            Poll::Ready(Ok(())) => Pin::new(&mut self.inner).poll_shutdown(cx),
+            other => other,
+        }
+    }
+}
+
+impl<S: AsyncRead + Unpin> AsyncRead for EncryptedStream<S> {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        dst: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let this = &mut *self;
+        loop {
+            match &mut this.read_state {
+                ReadState::Done => return Poll::Ready(Ok(())),
+                ReadState::Plain { buf, pos } => {
+                    if *pos < buf.len() {
+                        let take = dst.remaining().min(buf.len() - *pos);
+                        dst.put_slice(&buf[*pos..*pos + take]);
+                        *pos += take;
+                        return Poll::Ready(Ok(()));
+                    }
+                    this.read_state = ReadState::Len {
+                        buf: [0; LEN_PREFIX],
+                        filled: 0,
+                    };
+                }
+                ReadState::Len { buf, filled } => {
+                    let mut rb = ReadBuf::new(&mut buf[*filled..]);
+                    match Pin::new(&mut this.inner).poll_read(cx, &mut rb) {
+                        Poll::Ready(Ok(())) => {
+                            let n = rb.filled().len();
+                            if n == 0 {
+                                if *filled != 0 || !this.final_segment_received {
+                                    return Poll::Ready(Err(io::Error::new(
+                                        io::ErrorKind::UnexpectedEof,
+                                        "encrypted stream ended before its final segment",
+                                    )));
+                                }
+                                this.read_state = ReadState::Done;
+                                return Poll::Ready(Ok(()));
+                            }
+                            *filled += n;
+                            if *filled == LEN_PREFIX {
+                                let len = u32::from_le_bytes(*buf) as usize;
+                                let max_len = BLOCK_SIZE + TAG_LEN;
+                                if len > max_len {
+                                    return Poll::Ready(Err(io::Error::new(
+                                        io::ErrorKind::InvalidData,
+                                        "encrypted stream segment length prefix exceeds maximum",
+                                    )));
+                                }
+                                this.read_state = ReadState::Body {
+                                    buf: vec![0; len],
+                                    filled: 0,
+                                };
+                            }
+                        }
+                        Poll::Ready(Err(e)) => return Poll::Ready(Err(e)),
+                        Poll::Pending => return Poll::Pending,
+                    }
+                }
+                ReadState::Body { buf, filled } => {
+                    let mut rb = ReadBuf::new(&mut buf[*filled..]);
+                    match Pin::new(&mut this.inner).poll_read(cx, &mut rb) {
+                        Poll::Ready(Ok(())) => {
+                            let n = rb.filled().len();
+                            if n == 0 {
+                                return Poll::Ready(Err(io::Error::new(
+                                    io::ErrorKind::UnexpectedEof,
+                                    "encrypted stream truncated mid-segment",
+                                )));
+                            }
+                            *filled += n;
+                            if *filled == buf.len() {
+                                let sealed_size = BLOCK_SIZE + TAG_LEN;
+                                let is_last = buf.len() < sealed_size;
+
+                                let mut segment = std::mem::take(buf);
+                                let plaintext = this
+                                    .decryptor
+                                    .open_segment(&mut segment, is_last)
+                                    .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?
+                                    .to_vec();
+
+                                this.final_segment_received = is_last;
+                                this.read_state = ReadState::Plain {
+                                    buf: plaintext,
+                                    pos: 0,
+                                };
+                            }
+                        }
+                        Poll::Ready(Err(e)) => return Poll::Ready(Err(e)),
+                        Poll::Pending => return Poll::Pending,
+                    }
+                }
+            }
+        }
+    }
+}
+
+impl<S: AsyncRead + AsyncWrite + Unpin> EncryptedStream<S> {
+    /// Split into independent owned read/write halves that can be
+    /// driven concurrently, e.g. on separate `tokio` tasks. Recombine
+    /// with [`tokio::io::ReadHalf::unsplit`].
+    pub fn split(self) -> (tokio::io::ReadHalf<Self>, tokio::io::WriteHalf<Self>) {
+        tokio::io::split(self)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::crypto::{mnemonic, CryptoScheme};
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    async fn test_chunk_key() -> ChunkKey {
+        let (_, key_source) =
+            mnemonic::generate_mnemonic(mnemonic::DEFAULT_ENTROPY_BITS, "".to_string().into())
+                .expect("generate_mnemonic");
+        key_source.chunk_key().expect("chunk_key")
+    }
+
+    // Two peers, each wrapping one end of a duplex pair. Per the module
+    // docs, a{_to_b,b_to_a} must be *swapped* between the two peers --
+    // this also exercises that the two directions don't collide, since
+    // peer_b has to correctly decrypt what peer_a sealed.
+    async fn roundtrip(payload: Vec<u8>) {
+        let crypto = test_chunk_key().await;
+        let a_to_b = (ObjectId::default(), [1; 12]);
+        let b_to_a = (ObjectId::default(), [2; 12]);
+
+        let (a, b) = tokio::io::duplex(BLOCK_SIZE * 2);
+        let mut peer_a = EncryptedStream::new(a, &crypto, a_to_b, b_to_a).unwrap();
+        let mut peer_b = EncryptedStream::new(b, &crypto, b_to_a, a_to_b).unwrap();
+
+        let expected = payload.clone();
+        let writer = tokio::spawn(async move {
+            peer_a.write_all(&payload).await.unwrap();
+            peer_a.shutdown().await.unwrap();
+        });
+
+        let mut received = Vec::new();
+        peer_b.read_to_end(&mut received).await.unwrap();
+        writer.await.unwrap();
+
+        assert_eq!(received, expected);
+    }
+
+    #[tokio::test]
+    async fn roundtrips_a_partial_block() {
+        roundtrip(b"a message shorter than one block".to_vec()).await;
+    }
+
+    #[tokio::test]
+    async fn roundtrips_exactly_one_full_block() {
+        // Regression test: a payload that is an exact multiple of
+        // BLOCK_SIZE must still end in a segment explicitly marked
+        // `is_last`, or a truncated transfer of this exact shape would
+        // read back as a clean EOF instead of an error.
+        roundtrip(vec![0x5a; BLOCK_SIZE]).await;
+    }
+
+    #[tokio::test]
+    async fn roundtrips_empty_payload() {
+        roundtrip(Vec::new()).await;
+    }
+
+    #[tokio::test]
+    async fn oversized_length_prefix_is_rejected_without_allocating() {
+        // A corrupt or hostile peer can claim any length up to u32::MAX
+        // in the unauthenticated length prefix; it must be rejected
+        // before a buffer of that size is ever allocated.
+        let crypto = test_chunk_key().await;
+        let read_pair = (ObjectId::default(), [3; 12]);
+        let write_pair = (ObjectId::default(), [4; 12]);
+
+        let (mut raw, stream_half) = tokio::io::duplex(64);
+        let mut stream = EncryptedStream::new(stream_half, &crypto, write_pair, read_pair).unwrap();
+
+        let bogus_len = (BLOCK_SIZE + TAG_LEN + 1) as u32;
+        raw.write_all(&bogus_len.to_le_bytes()).await.unwrap();
+
+        let mut buf = [0u8; 1];
+        let err = stream.read(&mut buf).await.unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    }
+}
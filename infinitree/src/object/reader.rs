@@ -1,12 +1,12 @@
-use super::{Result, WriteObject};
+use super::{segment, Result, WriteObject};
 use crate::{
     backends::Backend,
     compress,
     crypto::{ChunkKey, CryptoProvider},
-    ChunkPointer,
+    ChunkPointer, ObjectId,
 };
 
-use std::sync::Arc;
+use std::{io, sync::Arc};
 
 pub trait Reader: Send {
     fn read_chunk<'target>(
@@ -50,3 +50,34 @@ impl Reader for AEADReader {
         Ok(&target[..size])
     }
 }
+
+impl AEADReader {
+    /// Decrypt a segmented object written by
+    /// [`AEADWriter::write_segmented`](super::AEADWriter::write_segmented),
+    /// writing each plaintext segment to `sink` as soon as it's
+    /// authenticated, instead of materializing the whole object like
+    /// `read_chunk` does. `base_iv` and `segment_size` must be the same
+    /// values the object was sealed with.
+    ///
+    /// `source` supplies the length-prefixed segment frames directly --
+    /// this deliberately does not go through `Backend::read_object`,
+    /// since a segmented object's on-disk framing already carries its
+    /// own boundaries and doesn't need the backend's object abstraction
+    /// to know where it ends.
+    ///
+    /// Truncation is caught here, not silently returned as a short
+    /// read: a missing final frame surfaces as an I/O error reading the
+    /// next length prefix, and a frame that lies about being the final
+    /// one fails authentication, since `is_last` is bound into each
+    /// segment's associated data.
+    pub fn read_segmented(
+        &mut self,
+        object_id: &ObjectId,
+        base_iv: [u8; 12],
+        segment_size: usize,
+        source: impl io::Read,
+        sink: &mut impl io::Write,
+    ) -> Result<()> {
+        segment::read_segmented(&self.crypto, object_id, base_iv, segment_size, source, sink)
+    }
+}
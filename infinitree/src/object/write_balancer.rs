@@ -2,11 +2,25 @@ use super::{ObjectError, Result, Writer};
 use crate::{crypto::Digest, ChunkPointer};
 
 use flume as mpsc;
+use std::sync::{
+    atomic::{AtomicUsize, Ordering},
+    Arc,
+};
+
+/// A pooled writer plus a running count of bytes handed to it but not
+/// yet confirmed written. The count is only raised while `write_chunk`
+/// holds the slot, so a slot waiting in the queue reads 0 unless its
+/// counter was set elsewhere; with every idle slot at 0, `least_loaded`
+/// takes the first idle writer.
+struct Slot<W> {
+    writer: W,
+    pending: Arc<AtomicUsize>,
+}
 
 #[derive(Clone)]
 pub struct RoundRobinBalancer<W> {
-    enqueue: mpsc::Sender<W>,
-    dequeue: mpsc::Receiver<W>,
+    enqueue: mpsc::Sender<Slot<W>>,
+    dequeue: mpsc::Receiver<Slot<W>>,
     writers: usize,
 }
 
@@ -16,7 +30,10 @@ impl<W: 'static + Writer + Clone> RoundRobinBalancer<W> {
 
         for _ in 0..writers {
             enqueue
-                .send(writer.clone())
+                .send(Slot {
+                    writer: writer.clone(),
+                    pending: Arc::new(AtomicUsize::new(0)),
+                })
                 .map_err(|_| ObjectError::Fatal)?;
         }
 
@@ -26,24 +43,162 @@ impl<W: 'static + Writer + Clone> RoundRobinBalancer<W> {
             writers,
         })
     }
+
+    /// Drain every writer that's currently idle and hand back the one
+    /// with the least work pending, putting the rest back in the
+    /// queue. Falls back to a blocking `recv` -- and so to strict FIFO
+    /// order -- when every writer is busy, same as before.
+    fn least_loaded(&self) -> Result<Slot<W>> {
+        let mut idle = Vec::with_capacity(self.writers);
+        while let Ok(slot) = self.dequeue.try_recv() {
+            idle.push(slot);
+        }
+
+        if idle.is_empty() {
+            return self.dequeue.recv().map_err(|_| ObjectError::Fatal);
+        }
+
+        let best = idle
+            .iter()
+            .enumerate()
+            .min_by_key(|(_, slot)| slot.pending.load(Ordering::Acquire))
+            .map(|(i, _)| i)
+            .expect("idle is non-empty");
+
+        let chosen = idle.swap_remove(best);
+        for slot in idle {
+            self.enqueue.send(slot).map_err(|_| ObjectError::Fatal)?;
+        }
+
+        Ok(chosen)
+    }
 }
 
 impl<W: 'static + Writer> Writer for RoundRobinBalancer<W> {
     fn write_chunk(&mut self, hash: &Digest, data: &[u8]) -> Result<ChunkPointer> {
-        let mut writer = self.dequeue.recv().map_err(|_| ObjectError::Fatal)?;
+        let slot = self.least_loaded()?;
+        let Slot { mut writer, pending } = slot;
+
+        pending.fetch_add(data.len(), Ordering::AcqRel);
         let result = writer.write_chunk(hash, data);
-        self.enqueue.send(writer).map_err(|_| ObjectError::Fatal)?;
+        pending.fetch_sub(data.len(), Ordering::AcqRel);
+
+        self.enqueue
+            .send(Slot { writer, pending })
+            .map_err(|_| ObjectError::Fatal)?;
 
         result
     }
 
     fn flush(&mut self) -> Result<()> {
         for _ in 0..self.writers {
-            let mut writer = self.dequeue.recv().map_err(|_| ObjectError::Fatal)?;
-            writer.flush()?;
-            self.enqueue.send(writer).map_err(|_| ObjectError::Fatal)?;
+            let mut slot = self.dequeue.recv().map_err(|_| ObjectError::Fatal)?;
+            slot.writer.flush()?;
+            self.enqueue.send(slot).map_err(|_| ObjectError::Fatal)?;
         }
 
         Ok(())
     }
+
+    /// Fan the hint out across every idle writer in the pool, so each
+    /// can pre-size its own object buffer for its roughly even share of
+    /// the burst.
+    fn size_hint(&mut self, total: usize) {
+        let per_writer = total / self.writers.max(1);
+
+        let mut idle = Vec::with_capacity(self.writers);
+        while let Ok(mut slot) = self.dequeue.try_recv() {
+            slot.writer.size_hint(per_writer);
+            idle.push(slot);
+        }
+
+        for slot in idle {
+            self.return_slot(slot);
+        }
+    }
+}
+
+impl<W> RoundRobinBalancer<W> {
+    /// Return a slot to the queue. The hint it was given is best-effort
+    /// and can be skipped, but the slot itself -- its writer and pending
+    /// counter -- must never be dropped: losing one here would
+    /// permanently shrink the pool and could eventually deadlock
+    /// flush's writers-count recv loop. If a concurrent write_chunk has
+    /// filled the channel back up, fall back to a blocking send rather
+    /// than discard the slot.
+    fn return_slot(&self, slot: Slot<W>) {
+        if let Err(mpsc::TrySendError::Full(slot)) = self.enqueue.try_send(slot) {
+            let _ = self.enqueue.send(slot);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::object::test::NullStorage;
+
+    #[test]
+    fn write_chunk_round_trips_through_every_writer() {
+        let mut balancer = RoundRobinBalancer::new(NullStorage::default(), 3).unwrap();
+
+        for i in 0..10u8 {
+            balancer
+                .write_chunk(&Digest::default(), &[i; 8])
+                .unwrap();
+        }
+
+        balancer.flush().unwrap();
+    }
+
+    #[test]
+    fn least_loaded_prefers_the_writer_with_less_pending_work() {
+        let balancer = RoundRobinBalancer::new(NullStorage::default(), 2).unwrap();
+
+        // Drain both slots and put them back with uneven pending counts,
+        // then confirm least_loaded hands back the lighter one.
+        let light = balancer.dequeue.recv().unwrap();
+        let heavy = balancer.dequeue.recv().unwrap();
+        light.pending.store(1, Ordering::Relaxed);
+        heavy.pending.store(100, Ordering::Relaxed);
+        let light_pending = light.pending.clone();
+
+        balancer.enqueue.send(heavy).unwrap();
+        balancer.enqueue.send(light).unwrap();
+
+        let chosen = balancer.least_loaded().unwrap();
+        assert!(Arc::ptr_eq(&chosen.pending, &light_pending));
+    }
+
+    #[test]
+    fn size_hint_fans_out_without_panicking() {
+        let mut balancer = RoundRobinBalancer::new(NullStorage::default(), 4).unwrap();
+        balancer.size_hint(4096);
+        // All four slots must have made it back into the queue.
+        assert_eq!(balancer.dequeue.len(), 4);
+    }
+
+    #[test]
+    fn return_slot_never_drops_a_slot_when_try_send_is_full() {
+        // A rendezvous (zero-capacity) channel guarantees try_send fails
+        // with Full whenever no receiver is waiting, forcing return_slot
+        // down its blocking-send fallback -- proving the slot is handed
+        // back rather than silently discarded.
+        let (enqueue, dequeue) = mpsc::bounded::<Slot<NullStorage>>(0);
+        let balancer = RoundRobinBalancer {
+            enqueue,
+            dequeue: dequeue.clone(),
+            writers: 1,
+        };
+
+        let slot = Slot {
+            writer: NullStorage::default(),
+            pending: Arc::new(AtomicUsize::new(0)),
+        };
+
+        let receiver = std::thread::spawn(move || dequeue.recv().unwrap());
+        balancer.return_slot(slot);
+
+        receiver.join().unwrap();
+    }
 }
@@ -0,0 +1,107 @@
+use super::{segment, Result, WriteObject};
+use crate::{
+    backends::Backend,
+    compress,
+    crypto::{ChunkKey, CryptoProvider, Random, SystemRandom},
+    ChunkPointer, Digest, ObjectId,
+};
+
+use std::{io, sync::Arc};
+
+pub trait Writer: Send {
+    fn write_chunk(&mut self, hash: &Digest, data: &[u8]) -> Result<ChunkPointer>;
+    fn flush(&mut self) -> Result<()>;
+
+    /// Hint that roughly `total` bytes are about to be written in a
+    /// burst, so an implementation that pools multiple objects can
+    /// pre-size that pool up front instead of growing it one
+    /// `write_chunk` at a time. Purely advisory: the default no-op is
+    /// always a correct implementation, and callers must not rely on it
+    /// changing behavior.
+    fn size_hint(&mut self, _total: usize) {}
+}
+
+#[derive(Clone)]
+pub struct AEADWriter {
+    backend: Arc<dyn Backend>,
+    crypto: ChunkKey,
+    buffer: WriteObject,
+}
+
+impl AEADWriter {
+    pub fn new(backend: Arc<dyn Backend>, crypto: ChunkKey) -> Self {
+        AEADWriter {
+            backend,
+            crypto,
+            buffer: WriteObject::default(),
+        }
+    }
+
+    fn roll_object(&mut self) -> Result<()> {
+        let random = SystemRandom::new();
+        self.buffer.finalize(&random);
+        self.backend.write_object(&self.buffer)?;
+
+        self.buffer.clear();
+        *self.buffer.position_mut() = 0;
+        self.buffer.reset_id(&random);
+
+        Ok(())
+    }
+}
+
+impl Writer for AEADWriter {
+    fn write_chunk(&mut self, hash: &Digest, data: &[u8]) -> Result<ChunkPointer> {
+        if self.buffer.position() == 0 {
+            self.buffer.reset_id(&SystemRandom::new());
+        }
+
+        let object_id = *self.buffer.id();
+        let offset = self.buffer.position();
+
+        let written = compress::compress_into(data, self.buffer.tail_mut())?;
+        *self.buffer.position_mut() += written;
+
+        let sealed = &mut self.buffer.as_inner_mut()[offset..offset + written];
+        let pointer = self.crypto.encrypt_chunk(hash, &object_id, sealed);
+
+        if self.buffer.tail_mut().is_empty() {
+            self.roll_object()?;
+        }
+
+        Ok(pointer)
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        if self.buffer.position() > 0 {
+            self.roll_object()?;
+        }
+
+        Ok(())
+    }
+}
+
+impl AEADWriter {
+    /// Encrypt `source` as a segmented object, writing
+    /// `len[4] || ciphertext || tag` frames to `sink` as each
+    /// `segment_size` plaintext chunk is sealed, instead of buffering
+    /// the whole object like `write_chunk` does. Pair with
+    /// [`AEADReader::read_segmented`](super::AEADReader::read_segmented)
+    /// using the same `object_id`, `base_iv` and `segment_size` to read
+    /// it back.
+    ///
+    /// `sink` is written to directly rather than through `self.backend`,
+    /// for the same reason `read_segmented` reads from an arbitrary
+    /// source: the framing is self-describing, so the backend's
+    /// fixed-size object abstraction isn't needed here.
+    pub fn write_segmented(
+        &mut self,
+        object_id: &ObjectId,
+        base_iv: [u8; 12],
+        segment_size: usize,
+        source: impl io::Read,
+        sink: impl io::Write,
+    ) -> Result<()> {
+        segment::write_segmented(&self.crypto, object_id, base_iv, segment_size, source, sink)
+    }
+}